@@ -447,6 +447,22 @@ impl Serializable for ConfigParams {
     }
 }
 
+// _ (HashmapE 32 ^Cell) = ConfigParams raw map;
+
+define_HashmapE!{ConfigParamsMap, 32, Cell}
+
+impl ConfigParamsMap {
+    /// get raw config param slice by index
+    pub fn get_param(&self, index: u32) -> Result<Option<SliceData>> {
+        self.get_as_slice(&index)
+    }
+
+    /// set raw config param slice by index
+    pub fn set_param_raw(&mut self, index: u32, value: SliceData) -> Result<()> {
+        self.set_slice(&index, value)
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum ConfigParamEnum {
     ConfigParam0(ConfigParam0),
@@ -23,6 +23,14 @@ use ton_types::{
 /// trait for types used as Augment to calc aug on forks
 pub trait Augmentable: Clone + Default + Serializable + Deserializable {
     fn calc(&mut self, other: &Self) -> Result<()>;
+    /// subtracts other subtotal, returns false if result would be negative
+    fn calc_sub(&mut self, _other: &Self) -> Result<bool> {
+        fail!(
+            BlockError::InvalidOperation(
+                format!("subtraction is not supported for {}", std::any::type_name::<Self>())
+            )
+        )
+    }
 }
 /// trait for objects in hashmap to help get augmentation from object
 pub trait Augmentation<Y: Augmentable> {
@@ -100,6 +108,20 @@ macro_rules! define_HashmapAugE {
                     data,
                 })
             }
+            /// Rebuilds tree from leaves recalculating augmentation of all forks and root
+            pub fn recompute_augmentation(&mut self) -> Result<()> {
+                let mut map = Self::new();
+                self.iterate_slices(|key, mut slice| {
+                    let aug = <$y_type>::construct_from(&mut slice)?;
+                    let mut value = BuilderData::new();
+                    value.checked_append_references_and_data(&slice)?;
+                    <Self as $crate::hashmapaug::HashmapAugType<$k_type, $x_type, $y_type>>
+                        ::set_builder_serialized(&mut map, key, &value, &aug)?;
+                    Ok(true)
+                })?;
+                *self = map;
+                Ok(())
+            }
             /// split map by key
             pub fn split(&self, key: &SliceData) -> Result<(Self, Self)> {
                 let (left, right) = self.hashmap_split(key)?;
@@ -120,6 +120,60 @@ where
     }
 }
 
+/// Builds dictionary from ordered map, keys must serialize to exactly bit_len bits
+pub fn hashmap_from_btree<K, V>(map: &std::collections::BTreeMap<K, V>, bit_len: usize) -> Result<HashmapE>
+where
+    K: Serializable,
+    V: Serializable
+{
+    let mut dictionary = HashmapE::with_bit_len(bit_len);
+    for (key, value) in map.iter() {
+        let key = key.write_to_new_cell()?;
+        if key.length_in_bits() != bit_len {
+            fail!(BlockError::InvalidArg(
+                format!("key has {} bits, but dictionary requires {}", key.length_in_bits(), bit_len)
+            ))
+        }
+        dictionary.set_builder(key.into_cell()?.into(), &value.write_to_new_cell()?)?;
+    }
+    Ok(dictionary)
+}
+
+/// Collects dictionary items into ordered map
+pub fn btree_from_hashmap<K, V>(dictionary: &HashmapE) -> Result<std::collections::BTreeMap<K, V>>
+where
+    K: Ord + Deserializable,
+    V: Deserializable
+{
+    let mut map = std::collections::BTreeMap::new();
+    dictionary.iterate_slices(|ref mut key, ref mut value| {
+        map.insert(K::construct_from(key)?, V::construct_from(value)?);
+        Ok(true)
+    })?;
+    Ok(map)
+}
+
+/// Rebuilds dictionary converting each value from Old to New type, keys are kept as is
+pub fn migrate_hashmap<Old, New, F>(old: &HashmapE, bit_len: usize, mut convert: F) -> Result<HashmapE>
+where
+    Old: Deserializable,
+    New: Serializable,
+    F: FnMut(Old) -> Result<New>
+{
+    if old.bit_len() != bit_len {
+        fail!(BlockError::InvalidArg(
+            format!("dictionary has {} bits keys, but {} is expected", old.bit_len(), bit_len)
+        ))
+    }
+    let mut dictionary = HashmapE::with_bit_len(bit_len);
+    old.iterate_slices(|key, ref mut value| {
+        let value = convert(Old::construct_from(value)?)?;
+        dictionary.set_builder(key, &value.write_to_new_cell()?)?;
+        Ok(true)
+    })?;
+    Ok(dictionary)
+}
+
 impl Serializable for HashmapE {
     fn write_to(&self, cell: &mut BuilderData) -> Result<()> {
         self.write_hashmap_data(cell)?;
@@ -143,11 +197,59 @@ pub trait Serializable {
         Ok(cell)
     }
 
+    /// Serializes object and checks depth of resulting cell tree after it is built,
+    /// failing if it exceeds max_depth. Depth of cell without references is 0.
+    /// It does not stop runaway serialization, only rejects too deep result
+    fn serialize_depth_checked(&self, max_depth: usize) -> Result<Cell> {
+        let cell = self.serialize()?;
+        let depth = cell.repr_depth() as usize;
+        if depth > max_depth {
+            fail!(BlockError::InvalidData(
+                format!("cell tree depth {} exceeds limit {}", depth, max_depth)
+            ))
+        }
+        Ok(cell)
+    }
+
+    /// Pads cell with zero bits so value starts at offset multiple of align_bits.
+    /// Padding depends only on offset in cell, so read_from_aligned skips the same bits
+    fn write_to_aligned(&self, cell: &mut BuilderData, align_bits: usize) -> Result<()> {
+        if align_bits == 0 {
+            fail!(BlockError::InvalidArg("alignment must not be zero".to_string()))
+        }
+        let pad = (align_bits - cell.length_in_bits() % align_bits) % align_bits;
+        cell.append_raw(&vec![0; (pad + 7) / 8], pad)?;
+        self.write_to(cell)
+    }
+
+    /// Serializes to new builder. Size estimation is not available,
+    /// so default path without preallocation is used, override it where size is known
+    fn write_to_reserved(&self) -> Result<BuilderData> {
+        self.write_to_new_cell()
+    }
+
     fn write_to_bytes(&self) -> Result<Vec<u8>> {
         let cell = self.serialize()?;
         ton_types::serialize_toc(&cell)
     }
 
+    fn write_to_bytes_limited(&self, max_bytes: usize) -> Result<Vec<u8>> {
+        let bytes = self.write_to_bytes()?;
+        if bytes.len() > max_bytes {
+            fail!(BlockError::InvalidArg(
+                format!("serialized size {} exceeds limit {} bytes", bytes.len(), max_bytes)
+            ))
+        }
+        Ok(bytes)
+    }
+
+    /// Writes bag of cells directly into writer without intermediate buffer
+    fn write_to_writer<W: std::io::Write>(&self, w: &mut W) -> Result<()> {
+        let cell = self.serialize()?;
+        ton_types::BagOfCells::with_root(&cell).write_to(w, false)?;
+        Ok(())
+    }
+
     fn write_to_file(&self, file_name: impl AsRef<std::path::Path>) -> Result<()> {
         let bytes = self.write_to_bytes()?;
         std::fs::write(file_name.as_ref(), bytes)?;
@@ -157,6 +259,36 @@ pub trait Serializable {
     fn serialize(&self) -> Result<Cell> {
         self.write_to_new_cell()?.into_cell()
     }
+
+    /// Alias of GetRepresentationHash::hash: cell tree is built but no bag of cells bytes
+    /// are produced, so result is the same as hash of fully built cell
+    fn hash_streaming(&self) -> Result<UInt256> where Self: std::fmt::Debug + Sized {
+        GetRepresentationHash::hash(self)
+    }
+
+    /// Counts distinct cells of serialized tree, shared cells are counted once
+    fn total_cells(&self) -> Result<usize> {
+        let mut visited = std::collections::HashSet::new();
+        let mut stack = vec![self.serialize()?];
+        while let Some(cell) = stack.pop() {
+            if visited.insert(cell.repr_hash()) {
+                for i in 0..cell.references_count() {
+                    stack.push(cell.reference(i)?);
+                }
+            }
+        }
+        Ok(visited.len())
+    }
+
+    /// Returns representation hash usable as stable, but semantically arbitrary, ordering key
+    fn order_key(&self) -> Result<UInt256> {
+        Ok(self.serialize()?.repr_hash())
+    }
+
+    /// Compares objects by representation hash, which does not depend on cell sharing
+    fn logical_eq(&self, other: &Self) -> Result<bool> where Self: Sized {
+        Ok(self.serialize()?.repr_hash() == other.serialize()?.repr_hash())
+    }
 }
 
 pub trait Deserializable: Default {
@@ -165,6 +297,62 @@ pub trait Deserializable: Default {
         x.read_from(slice)?;
         Ok(x)
     }
+    /// constructs object and returns unconsumed bits and references for further parsing
+    fn construct_from_with_rest(slice: &mut SliceData) -> Result<(Self, SliceData)> {
+        let x = Self::construct_from(slice)?;
+        Ok((x, slice.clone()))
+    }
+    /// constructs object and returns range of bits it occupied in current cell
+    fn read_from_tracked(slice: &mut SliceData) -> Result<(Self, std::ops::Range<usize>)> {
+        let start = slice.pos();
+        let x = Self::construct_from(slice)?;
+        Ok((x, start..slice.pos()))
+    }
+    /// skips zero padding written by write_to_aligned and constructs object
+    fn read_from_aligned(slice: &mut SliceData, align_bits: usize) -> Result<Self> {
+        if align_bits == 0 {
+            fail!(BlockError::InvalidArg("alignment must not be zero".to_string()))
+        }
+        let pad = (align_bits - slice.pos() % align_bits) % align_bits;
+        slice.get_next_bits(pad)?;
+        Self::construct_from(slice)
+    }
+    /// constructs object and returns counts of remaining bits and references in slice
+    fn construct_from_checked(slice: &mut SliceData) -> Result<(Self, usize, usize)> {
+        let x = Self::construct_from(slice)?;
+        Ok((x, slice.remaining_bits(), slice.remaining_references()))
+    }
+    /// constructs object checking it consumed exactly expected_bits of current cell
+    fn construct_expecting_bits(slice: &mut SliceData, expected_bits: usize) -> Result<Self> {
+        let start = slice.pos();
+        let x = Self::construct_from(slice)?;
+        let consumed = slice.pos() - start;
+        if consumed != expected_bits {
+            fail!(BlockError::InvalidData(
+                format!("{} consumed {} bits instead of {}", std::any::type_name::<Self>(), consumed, expected_bits)
+            ))
+        }
+        Ok(x)
+    }
+    /// constructs object and returns it only if validation passes
+    fn construct_validated<F: FnOnce(&Self) -> Result<()>>(slice: &mut SliceData, validate: F) -> Result<Self> {
+        let x = Self::construct_from(slice)?;
+        validate(&x)?;
+        Ok(x)
+    }
+    /// constructs object collecting non-fatal issues instead of failing on them,
+    /// default implementation reports only unconsumed data
+    fn construct_lenient(slice: &mut SliceData) -> Result<(Self, Vec<String>)> {
+        let x = Self::construct_from(slice)?;
+        let mut warnings = Vec::new();
+        if slice.remaining_bits() != 0 || slice.remaining_references() != 0 {
+            warnings.push(format!(
+                "trailing data: {} bits and {} references",
+                slice.remaining_bits(), slice.remaining_references()
+            ));
+        }
+        Ok((x, warnings))
+    }
     fn construct_from_cell(cell: Cell) -> Result<Self> {
         Self::construct_from(&mut cell.into())
     }
@@ -176,6 +364,37 @@ pub trait Deserializable: Default {
         let cell = ton_types::deserialize_tree_of_cells(&mut std::io::Cursor::new(bytes))?;
         Self::construct_from(&mut cell.into())
     }
+    /// constructs object converting panics in lower layers into error, for untrusted data.
+    /// Unwinding support adds no cost on success path, but panic hook is still called on failure
+    fn construct_from_bytes_catch_unwind(data: &[u8]) -> Result<Self> {
+        match std::panic::catch_unwind(|| Self::construct_from_bytes(data)) {
+            Ok(result) => result,
+            Err(payload) => {
+                let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown panic".to_string());
+                fail!(BlockError::InvalidData(
+                    format!("panic while deserializing {}: {}", std::any::type_name::<Self>(), message)
+                ))
+            }
+        }
+    }
+    /// constructs object from bag of cells rejecting it if it declares more than max_cells cells
+    fn construct_from_bytes_limited(data: &[u8], max_cells: usize) -> Result<Self> {
+        let cells_count = boc_cells_count(data)?;
+        if cells_count > max_cells {
+            fail!(BlockError::InvalidData(
+                format!("bag of cells contains {} cells, but limit is {}", cells_count, max_cells)
+            ))
+        }
+        Self::construct_from_bytes(data)
+    }
+    /// reads bag of cells from reader and constructs object from its root
+    fn construct_from_reader<R: std::io::Read>(r: &mut R) -> Result<Self> {
+        let cell = ton_types::deserialize_tree_of_cells(r)
+            .map_err(|err| BlockError::InvalidData(format!("cannot read bag of cells: {}", err)))?;
+        Self::construct_from(&mut cell.into())
+    }
     /// adapter for tests
     fn construct_from_file(file_name: impl AsRef<std::path::Path>) -> Result<Self> {
         let bytes = std::fs::read(file_name.as_ref())?;
@@ -203,6 +422,22 @@ pub trait Deserializable: Default {
     }
 }
 
+// reads count of cells from bag of cells header
+fn boc_cells_count(data: &[u8]) -> Result<usize> {
+    if data.len() < 6 {
+        fail!(BlockError::InvalidData("bag of cells header is too short".to_string()))
+    }
+    let size = match u32::from_be_bytes([data[0], data[1], data[2], data[3]]) {
+        0xb5ee9c72 => (data[4] & 7) as usize,
+        0x68ff65f3 | 0xacc3a728 => data[4] as usize,
+        magic => fail!(BlockError::InvalidData(format!("unknown bag of cells magic {:x}", magic)))
+    };
+    if size == 0 || size > 4 || data.len() < 6 + size {
+        fail!(BlockError::InvalidData(format!("wrong size of cells count field {}", size)))
+    }
+    Ok(data[6..6 + size].iter().fold(0, |count, byte| (count << 8) | *byte as usize))
+}
+
 pub trait MaybeSerialize {
     fn write_maybe_to(&self, cell: &mut BuilderData) -> Result<()>;
 }
@@ -257,6 +492,46 @@ pub trait MaybeDeserialize {
 
 impl<T: Deserializable> MaybeDeserialize for T {}
 
+/// Writes presence bit and value in reference if any
+pub fn append_maybe_ref<T: Serializable>(value: &Option<T>, cell: &mut BuilderData) -> Result<()> {
+    match value {
+        Some(value) => {
+            cell.append_bit_one()?;
+            cell.checked_append_reference(value.serialize()?)?;
+        }
+        None => {
+            cell.append_bit_zero()?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads presence bit and value from reference if any
+pub fn read_maybe_ref<T: Deserializable>(slice: &mut SliceData) -> Result<Option<T>> {
+    match slice.get_next_bit()? {
+        true => Ok(Some(T::construct_from_reference(slice)?)),
+        false => Ok(None)
+    }
+}
+
+/// Writes tag of tag_bits width followed by value
+pub fn write_tagged<T: Serializable>(tag: u32, tag_bits: usize, value: &T, cell: &mut BuilderData) -> Result<()> {
+    if tag_bits > 32 || (tag as u64) >> tag_bits != 0 {
+        fail!(BlockError::InvalidArg(format!("tag {:x} does not fit into {} bits", tag, tag_bits)))
+    }
+    cell.append_bits(tag as usize, tag_bits)?;
+    value.write_to(cell)
+}
+
+/// Reads tag of tag_bits width followed by value
+pub fn read_tagged<T: Deserializable>(slice: &mut SliceData, tag_bits: usize) -> Result<(u32, T)> {
+    if tag_bits > 32 {
+        fail!(BlockError::InvalidArg(format!("tag of {} bits is longer than 32 bits", tag_bits)))
+    }
+    let tag = slice.get_next_int(tag_bits)? as u32;
+    Ok((tag, T::construct_from(slice)?))
+}
+
 pub trait GetRepresentationHash: Serializable + std::fmt::Debug {
     fn hash(&self) -> Result<UInt256> {
         match self.serialize() {
@@ -271,6 +546,48 @@ pub trait GetRepresentationHash: Serializable + std::fmt::Debug {
 
 impl<T: Serializable + std::fmt::Debug> GetRepresentationHash for T {}
 
+/// Wrapper ordering objects by representation hash, e.g. for BTreeMap keys
+#[derive(Clone, Debug)]
+pub struct OrderedByHash<T> {
+    key: UInt256,
+    value: T,
+}
+
+impl<T: Serializable> OrderedByHash<T> {
+    pub fn new(value: T) -> Result<Self> {
+        Ok(Self { key: value.order_key()?, value })
+    }
+    pub fn key(&self) -> &UInt256 {
+        &self.key
+    }
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T> PartialEq for OrderedByHash<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<T> Eq for OrderedByHash<T> {}
+
+impl<T> PartialOrd for OrderedByHash<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for OrderedByHash<T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key.as_slice().cmp(other.key.as_slice())
+    }
+}
+
 impl Deserializable for UInt256 {
     fn construct_from(slice: &mut SliceData) -> Result<Self> {
         slice.get_next_hash()
@@ -317,6 +634,18 @@ impl Serializable for () {
     }
 }
 
+impl<T> Deserializable for std::marker::PhantomData<T> {
+    fn read_from(&mut self, _cell: &mut SliceData) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<T> Serializable for std::marker::PhantomData<T> {
+    fn write_to(&self, _cell: &mut BuilderData) -> Result<()> {
+        Ok(())
+    }
+}
+
 pub fn id_from_key(key: &ed25519_dalek::PublicKey) -> u64 {
     let bytes = key.to_bytes();
     u64::from_be_bytes([ 
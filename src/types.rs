@@ -17,12 +17,12 @@ use std::fmt::{self, Display, Formatter};
 use std::marker::PhantomData;
 use std::ops::Deref;
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use num::{BigInt, BigUint, bigint::Sign, One, Zero};
 use num_traits::cast::ToPrimitive;
 use ton_types::{error, fail, Result,
-    BuilderData, Cell, CellType, IBitstring, HashmapE, HashmapType, SliceData, ExceptionCode, UInt256
+    BuilderData, Cell, CellType, IBitstring, HashmapE, HashmapType, SliceData, UInt256
 };
 
 use crate::{
@@ -77,6 +77,20 @@ macro_rules! define_VarIntegerN {
                 self.0.sign() != Sign::NoSign
             }
 
+            /// Parses optionally 0x-prefixed hex string
+            pub fn from_hex_str(string: &str) -> Result<Self> {
+                let hex = string.strip_prefix("0x").unwrap_or(string);
+                if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                    fail!(BlockError::InvalidArg(format!("invalid hex string {:?}", string)))
+                }
+                let value = match BigInt::parse_bytes(hex.as_bytes(), 16) {
+                    Some(value) => value,
+                    None => fail!(BlockError::InvalidArg(format!("invalid hex string {:?}", string)))
+                };
+                Self::check_serializable(&value)?;
+                Ok($varname(value))
+            }
+
             pub fn from_two_u128(hi: u128, lo: u128) -> Result<Self> {
                 let val = (BigInt::from(hi) << 128) | BigInt::from(lo);
                 Self::check_owerflow(&val)?;
@@ -88,7 +102,13 @@ macro_rules! define_VarIntegerN {
             }
 
             fn check_owerflow(value: &BigInt) -> Result<()> {
-                if Self::get_len(&value) > $N {
+                if value.sign() == Sign::Minus {
+                    fail!(
+                        BlockError::InvalidArg(
+                            format!("value {} of {} must not be negative", value, stringify!($varname))
+                        )
+                    )
+                } else if Self::get_len(&value) > $N {
                     fail!(
                         BlockError::InvalidArg(
                             format!("value is bigger than {} bytes", $N)
@@ -99,6 +119,19 @@ macro_rules! define_VarIntegerN {
                 }
             }
 
+            // checks value is non-negative and fits len field, i.e. is shorter than $N bytes
+            fn check_serializable(value: &BigInt) -> Result<()> {
+                Self::check_owerflow(value)?;
+                if Self::get_len(value) >= $N {
+                    fail!(
+                        BlockError::InvalidArg(
+                            format!("value of {} must be shorter than {} bytes", stringify!($varname), $N)
+                        )
+                    )
+                }
+                Ok(())
+            }
+
             // determine the size of the len field, using the formula from 3.3.4 VM 
             fn get_len_len() -> usize {
                 let max_bits = ($N - 1) as f64;
@@ -127,12 +160,33 @@ macro_rules! define_VarIntegerN {
                 Ok(BigInt::from_bytes_be(Sign::Plus, &cell.get_next_bytes(len)?))
             }
 
+            /// Reads value rejecting encodings longer than max_bytes
+            pub fn read_from_bounded(slice: &mut SliceData, max_bytes: usize) -> Result<Self> {
+                if max_bytes > $N {
+                    fail!(
+                        BlockError::InvalidArg(
+                            format!("bound {} is bigger than {} bytes", max_bytes, $N)
+                        )
+                    )
+                }
+                let len = slice.clone().get_next_int(Self::get_len_len())? as usize;
+                if len > max_bytes {
+                    fail!(
+                        BlockError::InvalidData(
+                            format!("{} length {} exceeds bound {}", stringify!($varname), len, max_bytes)
+                        )
+                    )
+                }
+                Self::construct_from(slice)
+            }
         }
 
         impl<T: Into<BigInt>> From<T> for $varname {
             fn from(value: T) -> Self {
                 let val = BigInt::from(value.into());
-                Self::check_owerflow(&val).expect("Integer overflow");
+                if let Err(err) = Self::check_owerflow(&val) {
+                    panic!("{}", err)
+                }
                 $varname(val)
             }
         }
@@ -192,6 +246,31 @@ macro_rules! define_VarIntegerN {
             }
         }
 
+        #[cfg(feature = "serde")]
+        impl $varname {
+            /// JSON representation as decimal string to avoid precision loss
+            pub fn to_json_value(&self) -> serde_json::Value {
+                serde_json::Value::String(self.0.to_string())
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for $varname {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+                serializer.serialize_str(&self.0.to_string())
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> serde::Deserialize<'de> for $varname {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+                let string = <String as serde::Deserialize>::deserialize(deserializer)?;
+                let value = string.parse::<BigInt>().map_err(serde::de::Error::custom)?;
+                Self::check_serializable(&value).map_err(serde::de::Error::custom)?;
+                Ok($varname(value))
+            }
+        }
+
         impl fmt::Display for $varname {
             fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
                 write!(f, "{}", &self.0)
@@ -210,14 +289,60 @@ macro_rules! define_VarIntegerN {
                 let bytes = ((0 as $tt).leading_zeros() / 8 - self.0.leading_zeros() / 8) as usize;
                 bits as usize + bytes * 8
             }
+            // count of significant bytes in value
+            fn bytes_len(&self) -> usize {
+                ((0 as $tt).leading_zeros() / 8 - self.0.leading_zeros() / 8) as usize
+            }
+            /// Adds values failing if sum does not fit into type's byte budget
+            pub fn checked_add(&self, other: &Self) -> Result<Self> {
+                match self.0.checked_add(other.0).map($varname) {
+                    Some(sum) if sum.bytes_len() <= $N => Ok(sum),
+                    _ => fail!(
+                        BlockError::InvalidArg(
+                            format!("sum of {} and {} is bigger than {} bytes of {}",
+                                self.0, other.0, $N, stringify!($varname))
+                        )
+                    )
+                }
+            }
+            /// Adds values clamping sum to maximum value fitting into type's byte budget
+            pub fn saturating_add(&self, other: &Self) -> Self {
+                let max = (1 as $tt).checked_shl($N * 8).map(|v| v - 1).unwrap_or(<$tt>::MAX);
+                $varname(std::cmp::min(self.0.saturating_add(other.0), max))
+            }
+            /// Reads value rejecting encodings longer than max_bytes
+            pub fn read_from_bounded(slice: &mut SliceData, max_bytes: usize) -> Result<Self> {
+                if max_bytes > $N {
+                    fail!(
+                        BlockError::InvalidArg(
+                            format!("bound {} is bigger than {} bytes", max_bytes, $N)
+                        )
+                    )
+                }
+                let bits = 8 - ($N as u8).leading_zeros();
+                let len = slice.clone().get_next_int(bits as usize)? as usize;
+                if len > max_bytes {
+                    fail!(
+                        BlockError::InvalidData(
+                            format!("{} length {} exceeds bound {}", stringify!($varname), len, max_bytes)
+                        )
+                    )
+                }
+                Self::construct_from(slice)
+            }
         }
 
         impl Serializable for $varname {
             fn write_to(&self, cell: &mut BuilderData) -> Result<()> {
                 let bits = 8 - ($N as u8).leading_zeros();
-                let bytes = ((0 as $tt).leading_zeros() / 8 - self.0.leading_zeros() / 8) as usize;
+                let bytes = self.bytes_len();
                 if bytes > $N {
-                    fail!(ExceptionCode::IntegerOverflow)
+                    fail!(
+                        BlockError::InvalidArg(
+                            format!("value {} of {} is {} bytes long, but must be not bigger than {} bytes",
+                                self.0, stringify!($varname), bytes, $N)
+                        )
+                    )
                 }
                 cell.append_bits(bytes, bits as usize)?;
                 let be_bytes = self.0.to_be_bytes();
@@ -237,6 +362,25 @@ macro_rules! define_VarIntegerN {
                 self.0 = <$tt>::from_le_bytes(bytes.as_slice().try_into()?);
                 Ok(())
             }
+
+            fn construct_lenient(slice: &mut SliceData) -> Result<(Self, Vec<String>)> {
+                let start = slice.pos();
+                let x = Self::construct_from(slice)?;
+                let mut warnings = Vec::new();
+                if slice.pos() - start != x.get_len() {
+                    warnings.push(format!(
+                        "non-canonical encoding: {} bits used instead of {}",
+                        slice.pos() - start, x.get_len()
+                    ));
+                }
+                if slice.remaining_bits() != 0 || slice.remaining_references() != 0 {
+                    warnings.push(format!(
+                        "trailing data: {} bits and {} references",
+                        slice.remaining_bits(), slice.remaining_references()
+                    ));
+                }
+                Ok((x, warnings))
+            }
         }
 
         impl From<$tt> for $varname {
@@ -245,6 +389,35 @@ macro_rules! define_VarIntegerN {
             }
         }
 
+        #[cfg(feature = "serde")]
+        impl $varname {
+            /// JSON representation as decimal string to avoid precision loss
+            pub fn to_json_value(&self) -> serde_json::Value {
+                serde_json::Value::String(self.0.to_string())
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for $varname {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+                serializer.serialize_str(&self.0.to_string())
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> serde::Deserialize<'de> for $varname {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+                let string = <String as serde::Deserialize>::deserialize(deserializer)?;
+                let value = $varname(string.parse::<$tt>().map_err(serde::de::Error::custom)?);
+                if value.bytes_len() > $N {
+                    return Err(serde::de::Error::custom(
+                        format!("value {} is bigger than {} bytes", value.0, $N)
+                    ))
+                }
+                Ok(value)
+            }
+        }
+
         impl fmt::Display for $varname {
             fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
                 write!(f, "{}", &self.0)
@@ -262,6 +435,9 @@ impl Augmentable for Grams {
         self.0 += &other.0;
         Ok(())
     }
+    fn calc_sub(&mut self, other: &Self) -> Result<bool> {
+        self.sub(other)
+    }
 }
 
 impl AddSub for Grams {
@@ -279,7 +455,39 @@ impl AddSub for Grams {
     }
 }
 
+impl AddSub for VarUInteger3 {
+    fn add(&mut self, other: &VarUInteger3) -> Result<()> {
+        *self = self.checked_add(other)?;
+        Ok(())
+    }
+    fn sub(&mut self, other: &VarUInteger3) -> Result<bool> {
+        if self.0 >= other.0 {
+            self.0 -= other.0;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+}
+
+impl AddSub for VarUInteger7 {
+    fn add(&mut self, other: &VarUInteger7) -> Result<()> {
+        *self = self.checked_add(other)?;
+        Ok(())
+    }
+    fn sub(&mut self, other: &VarUInteger7) -> Result<bool> {
+        if self.0 >= other.0 {
+            self.0 -= other.0;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+}
+
 impl Grams {
+    pub const ZERO: Grams = Grams(0);
+
     pub const fn shr(mut self, shr: u8) -> Self {
         self.0 >>= shr as usize;
         self
@@ -304,6 +512,266 @@ impl Grams {
     pub const fn is_zero(&self) -> bool {
         self.0 == 0
     }
+
+    pub const fn is_nonzero(&self) -> bool {
+        self.0 != 0
+    }
+
+    /// Returns error for zero amount, i.e. for zero-value transfers
+    pub fn require_nonzero(&self) -> Result<&Self> {
+        if self.is_zero() {
+            fail!(BlockError::InvalidArg("amount of grams must not be zero".to_string()))
+        }
+        Ok(self)
+    }
+
+    /// Converts signed integer rejecting negative values and values bigger than 15 bytes
+    pub fn checked_from<T: Into<i128>>(value: T) -> Result<Self> {
+        let value = value.into();
+        if value < 0 {
+            fail!(BlockError::InvalidArg(format!("amount of grams {} must not be negative", value)))
+        }
+        Self::from_nanograms(value as u128)
+    }
+
+    /// Constructs amount from explicit count of nanograms failing if it is bigger than 15 bytes
+    pub fn from_nanograms(nanograms: u128) -> Result<Self> {
+        let grams = Grams(nanograms);
+        if grams.bytes_len() > 15 {
+            fail!(BlockError::InvalidArg(format!("value {} of Grams is bigger than 15 bytes", nanograms)))
+        }
+        Ok(grams)
+    }
+
+    /// Returns amount in nanograms, None if value is not representable in 15 bytes
+    pub fn to_nanograms(&self) -> Option<u128> {
+        if self.bytes_len() > 15 {
+            None
+        } else {
+            Some(self.0)
+        }
+    }
+
+    /// Bounds amount to [min, max] window, e.g. for fee caps and floors
+    pub fn clamp_range(&self, min: &Grams, max: &Grams) -> Result<Grams> {
+        if min > max {
+            fail!(BlockError::InvalidArg(format!("min {} is bigger than max {}", min, max)))
+        }
+        Ok(self.clone().clamp(min.clone(), max.clone()))
+    }
+
+    /// Parses optionally 0x-prefixed hex string, e.g. balance from JSON-RPC
+    pub fn from_hex_str(string: &str) -> Result<Self> {
+        let hex = string.strip_prefix("0x").unwrap_or(string);
+        if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            fail!(BlockError::InvalidArg(format!("invalid hex string {:?}", string)))
+        }
+        let digits = hex.trim_start_matches('0');
+        match u128::from_str_radix(if digits.is_empty() { "0" } else { digits }, 16) {
+            Ok(value) if digits.len() <= 30 => Ok(Grams(value)),
+            _ => fail!(BlockError::InvalidArg(format!("hex value {:?} is bigger than 15 bytes", string)))
+        }
+    }
+
+    /// Rounds half-up to the nearest multiple of granularity
+    pub fn round_to(&self, granularity: &Grams) -> Result<Grams> {
+        if granularity.is_zero() {
+            fail!(BlockError::InvalidArg("granularity must not be zero".to_string()))
+        }
+        let rem = self.0 % granularity.0;
+        let base = self.0 - rem;
+        if rem < granularity.0 - rem {
+            return Ok(Grams(base))
+        }
+        match base.checked_add(granularity.0) {
+            Some(value) => Self::from_nanograms(value),
+            None => fail!(BlockError::InvalidArg(
+                format!("value {} of Grams rounded to {} overflows", self.0, granularity.0)
+            ))
+        }
+    }
+
+    /// Computes value * numerator / denominator rounding down,
+    /// intermediate product is calculated in BigInt so it can not overflow
+    pub fn percent(&self, numerator: u32, denominator: u32) -> Result<Grams> {
+        if denominator == 0 {
+            fail!(BlockError::InvalidArg("denominator must not be zero".to_string()))
+        }
+        let value = BigInt::from(self.0) * numerator / denominator;
+        match value.to_u128() {
+            Some(value) => Self::from_nanograms(value),
+            None => fail!(BlockError::InvalidArg(format!("value {} of Grams is bigger than 15 bytes", value)))
+        }
+    }
+
+    /// Formats amount for log diagnostics, e.g. "100000000 nano (4 bytes)"
+    pub fn debug_fmt(&self) -> String {
+        format!("{} nano ({} bytes)", self.0, self.bytes_len())
+    }
+
+    /// Formats amount scaled by decimals with grouped integer digits, e.g. 1,234.500000000
+    pub fn to_formatted_string(&self, decimals: u32, group_separator: char, decimal_point: char) -> String {
+        let decimals = decimals as usize;
+        let mut digits = self.0.to_string();
+        if digits.len() <= decimals {
+            digits = format!("{}{}", "0".repeat(decimals + 1 - digits.len()), digits);
+        }
+        let (int_part, frac_part) = digits.split_at(digits.len() - decimals);
+        let mut result = String::new();
+        for (i, digit) in int_part.chars().enumerate() {
+            if i != 0 && (int_part.len() - i) % 3 == 0 {
+                result.push(group_separator);
+            }
+            result.push(digit);
+        }
+        if decimals != 0 {
+            result.push(decimal_point);
+            result.push_str(frac_part);
+        }
+        result
+    }
+
+    /// Sums amounts into VarUInteger32 which can hold result beyond Grams range
+    pub fn add_into_var32(&self, other: &Grams) -> VarUInteger32 {
+        VarUInteger32(BigInt::from(self.0) + BigInt::from(other.0))
+    }
+
+    /// Deducts fee returning (amount after fee, fee applied). If balance is insufficient
+    /// for full fee the whole balance is taken as fee and remaining amount is zero
+    pub fn split_fee(&self, fee: &Grams) -> Result<(Grams, Grams)> {
+        if self.0 >= fee.0 {
+            Ok((Grams(self.0 - fee.0), fee.clone()))
+        } else {
+            Ok((Grams::zero(), self.clone()))
+        }
+    }
+
+    /// Writes zero amount with explicit length of zero bytes instead of canonical four zero bits.
+    /// This encoding is non-standard and intended only for interoperability with decoders
+    /// which mishandle empty value, use write_to in all other cases
+    pub fn write_zero_as_full(cell: &mut BuilderData, bytes: usize) -> Result<()> {
+        if bytes == 0 || bytes > 15 {
+            fail!(BlockError::InvalidArg(format!("length of zero grams {} must be in 1..=15", bytes)))
+        }
+        cell.append_bits(bytes, 4)?;
+        cell.append_raw(&vec![0; bytes], bytes * 8)?;
+        Ok(())
+    }
+
+    /// Returns canonical serialized bits, i.e. 4-bit length and value bytes, packed into bytes
+    /// with zero padding in the last nibble. Returns None for amounts longer than 15 bytes
+    pub fn serialized_bytes(&self) -> Option<Vec<u8>> {
+        let len = self.bytes_len();
+        if len > 15 {
+            return None
+        }
+        let be_bytes = self.0.to_be_bytes();
+        let mut result = Vec::with_capacity(len + 1);
+        let mut nibble = (len as u8) << 4;
+        for byte in &be_bytes[be_bytes.len() - len..] {
+            result.push(nibble | (byte >> 4));
+            nibble = byte << 4;
+        }
+        result.push(nibble);
+        Some(result)
+    }
+
+    /// Checks if slice starts with minimal encoding of amount, so re-serialization
+    /// gives the same bits. Non-minimal encodings have excess leading zero bytes
+    pub fn assert_canonical(slice: &SliceData) -> Result<()> {
+        let mut rest = slice.clone();
+        let value = Self::construct_from(&mut rest)?;
+        let consumed = slice.remaining_bits() - rest.remaining_bits();
+        if consumed != value.get_len() {
+            fail!(
+                BlockError::InvalidData(
+                    format!("Grams {} encoded in {} bits instead of {}", value, consumed, value.get_len())
+                )
+            )
+        }
+        Ok(())
+    }
+
+    /// Orders two serialized amounts without decoding them when lengths differ,
+    /// encodings are expected to be canonical
+    pub fn cmp_serialized(a: &SliceData, b: &SliceData) -> Result<Ordering> {
+        let mut a = a.clone();
+        let mut b = b.clone();
+        let len_a = a.get_next_int(4)? as usize;
+        let len_b = b.get_next_int(4)? as usize;
+        if len_a != len_b {
+            return Ok(len_a.cmp(&len_b))
+        }
+        Ok(a.get_next_bytes(len_a)?.cmp(&b.get_next_bytes(len_b)?))
+    }
+
+    /// Reads amount failing on zero, for fields which require positive value
+    pub fn read_positive(slice: &mut SliceData) -> Result<Self> {
+        let value = Self::construct_from(slice)?;
+        if value.is_zero() {
+            fail!(BlockError::InvalidData("amount of grams must be positive".to_string()))
+        }
+        Ok(value)
+    }
+
+    /// Reads amount as u128, any Grams encoding fits it
+    pub fn read_as_u128(slice: &mut SliceData) -> Result<u128> {
+        Ok(Self::construct_from(slice)?.0)
+    }
+}
+
+/// Grams amount validated against optional upper bound, serialized as Grams
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CheckedGrams {
+    value: Grams,
+    max: Option<Grams>,
+}
+
+impl CheckedGrams {
+    pub fn new(value: Grams, max: Option<Grams>) -> Result<Self> {
+        if let Some(max) = &max {
+            if &value > max {
+                fail!(BlockError::InvalidArg(format!("amount {} is bigger than {}", value, max)))
+            }
+        }
+        Ok(Self { value, max })
+    }
+    pub fn value(&self) -> &Grams {
+        &self.value
+    }
+    pub fn max(&self) -> Option<&Grams> {
+        self.max.as_ref()
+    }
+}
+
+impl Serializable for CheckedGrams {
+    fn write_to(&self, cell: &mut BuilderData) -> Result<()> {
+        self.value.write_to(cell)
+    }
+}
+
+/// Sets amount of nanograms in dictionary with Grams values
+pub fn set_grams<K: Serializable>(map: &mut HashmapE, key: &K, nanograms: u128) -> Result<()> {
+    let value = Grams::from_nanograms(nanograms)?.write_to_new_cell()?;
+    map.set_builder(key.serialize()?.into(), &value)?;
+    Ok(())
+}
+
+/// Rewrites non-canonical encodings of values in dictionary with Grams values canonically,
+/// returns count of fixed values
+pub fn normalize_grams_values(map: &mut HashmapE) -> Result<usize> {
+    let mut fixed = Vec::new();
+    map.iterate_slices(|key, mut slice| {
+        if Grams::assert_canonical(&slice).is_err() {
+            let value = Grams::construct_from(&mut slice)?;
+            fixed.push((key, value.write_to_new_cell()?));
+        }
+        Ok(true)
+    })?;
+    for (key, value) in &fixed {
+        map.set_builder(key.clone(), value)?;
+    }
+    Ok(fixed.len())
 }
 
 impl From<BigInt> for Grams {
@@ -343,6 +811,7 @@ impl From<u64> for Grams {
         Self(value as u128)
     }
 }
+/// Negative values are not checked, use Grams::checked_from to reject them
 impl From<i64> for Grams {
     fn from(value: i64) -> Self {
         Self(value as u128)
@@ -353,12 +822,34 @@ impl From<u32> for Grams {
         Self(value as u128)
     }
 }
+/// Negative values are not checked, use Grams::checked_from to reject them
 impl From<i32> for Grams {
     fn from(value: i32) -> Self {
         Self(value as u128)
     }
 }
 
+/// Smallest variable-length integer type able to hold value
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SmallestVarUint {
+    Grams(Grams),
+    VarUInteger32(VarUInteger32),
+}
+
+/// Chooses Grams if value fits 15 bytes, otherwise VarUInteger32
+pub fn smallest_var_uint(value: &BigInt) -> Result<SmallestVarUint> {
+    if value.sign() == Sign::Minus {
+        fail!(BlockError::InvalidArg(format!("value {} must not be negative", value)))
+    }
+    if let Some(grams) = value.to_u128().map(Grams) {
+        if grams.bytes_len() <= 15 {
+            return Ok(SmallestVarUint::Grams(grams))
+        }
+    }
+    VarUInteger32::check_serializable(value)?;
+    Ok(SmallestVarUint::VarUInteger32(VarUInteger32(value.clone())))
+}
+
 ///////////////////////////////////////////////////////////////////////////////
 ///
 /// munber ## N
@@ -383,9 +874,56 @@ macro_rules! define_NumberN_up32bit {
                 Ok($varname(value))
             }
 
+            pub fn from_bigint(value: &BigInt, max_value: u32) -> Result<Self> {
+                match value.to_u32() {
+                    Some(value) => Self::from_u32(value, max_value),
+                    None => fail!(BlockError::InvalidArg(
+                        format!("value: {} must be in range 0..={}", value, max_value)
+                    ))
+                }
+            }
+
             pub fn get_max_len() -> usize {
                 (((1 as u64) << $N) - 1) as usize
             }
+
+            /// Multiplies value, returns None if product does not fit into type's bit width
+            pub fn checked_mul(&self, rhs: u32) -> Option<Self> {
+                let product = self.0 as u64 * rhs as u64;
+                if product > Self::get_max_len() as u64 {
+                    None
+                } else {
+                    Some($varname(product as u32))
+                }
+            }
+
+            /// Writes value in little-endian byte order,
+            /// defined only for widths multiple of 8 bits
+            pub fn write_to_le(&self, cell: &mut BuilderData) -> Result<()> {
+                Self::check_le_width()?;
+                let bytes = self.0.to_le_bytes();
+                cell.append_raw(&bytes[..$N / 8], $N)?;
+                Ok(())
+            }
+
+            /// Reads value stored in little-endian byte order,
+            /// defined only for widths multiple of 8 bits
+            pub fn read_from_le(&mut self, slice: &mut SliceData) -> Result<()> {
+                Self::check_le_width()?;
+                let mut bytes = slice.get_next_bytes($N / 8)?;
+                bytes.resize(4, 0);
+                self.0 = u32::from_le_bytes(bytes.as_slice().try_into()?);
+                Ok(())
+            }
+
+            fn check_le_width() -> Result<()> {
+                if $N % 8 != 0 {
+                    fail!(BlockError::InvalidOperation(
+                        format!("little-endian order is not defined for {} bits", $N)
+                    ))
+                }
+                Ok(())
+            }
         }
 
         impl Default for $varname {
@@ -408,6 +946,29 @@ macro_rules! define_NumberN_up32bit {
             }
         }
 
+        #[cfg(feature = "serde")]
+        impl $varname {
+            /// JSON representation as number
+            pub fn to_json_value(&self) -> serde_json::Value {
+                serde_json::Value::from(self.0)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for $varname {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+                serializer.serialize_u32(self.0)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> serde::Deserialize<'de> for $varname {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+                let value = <u32 as serde::Deserialize>::deserialize(deserializer)?;
+                Self::from_u32(value, Self::get_max_len() as u32).map_err(serde::de::Error::custom)
+            }
+        }
+
         impl fmt::Display for $varname {
             fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
                 write!(
@@ -432,6 +993,42 @@ define_NumberN_up32bit!(Number13, 13);
 define_NumberN_up32bit!(Number16, 16);
 define_NumberN_up32bit!(Number32, 32);
 
+/// Writes sequence of values each in its declared bit width
+pub fn write_bit_fields(fields: &[(u32, usize)], cell: &mut BuilderData) -> Result<()> {
+    for (value, bits) in fields {
+        if *bits > 32 || (*value as u64) >> bits != 0 {
+            fail!(BlockError::InvalidArg(
+                format!("value: {} does not fit into {} bits", value, bits)
+            ))
+        }
+        cell.append_bits(*value as usize, *bits)?;
+    }
+    Ok(())
+}
+
+// widening From and narrowing TryFrom between each pair of NumberN types
+macro_rules! define_NumberN_conversions {
+    ( $narrow:ident, $( $wide:ident ),+ ) => {
+        $(
+            impl From<$narrow> for $wide {
+                fn from(value: $narrow) -> Self {
+                    $wide(value.0)
+                }
+            }
+
+            impl std::convert::TryFrom<$wide> for $narrow {
+                type Error = failure::Error;
+                fn try_from(value: $wide) -> Result<Self> {
+                    $narrow::from_u32(value.0, $narrow::get_max_len() as u32)
+                }
+            }
+        )+
+        define_NumberN_conversions!($( $wide ),+);
+    };
+    ( $widest:ident ) => {};
+}
+define_NumberN_conversions!(Number5, Number8, Number9, Number12, Number13, Number16, Number32);
+
 define_HashmapE!{ExtraCurrencyCollection, 32, VarUInteger32}
 
 impl From<HashmapE> for ExtraCurrencyCollection {
@@ -726,20 +1323,209 @@ impl<X: Default + Deserializable + Serializable> Serializable for InRefValue<X>
     }
 }
 
-impl<X: Default + Deserializable> Deserializable for Arc<X> {
+/// TL-B Either X ^X: flag bit, then value inline or in reference, second field is true for reference
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct EitherInlineOrRef<X: Default + Deserializable + Serializable>(pub X, pub bool);
+
+impl<X: Default + Deserializable + Serializable> EitherInlineOrRef<X> {
+    pub fn inline(inner: X) -> Self {
+        Self(inner, false)
+    }
+    pub fn in_ref(inner: X) -> Self {
+        Self(inner, true)
+    }
+    pub fn is_ref(&self) -> bool {
+        self.1
+    }
+    pub fn inner(self) -> X {
+        self.0
+    }
+}
+
+impl<X: Default + Deserializable + Serializable> Deserializable for EitherInlineOrRef<X> {
     fn construct_from(slice: &mut SliceData) -> Result<Self> {
-        Ok(Arc::new(X::construct_from(slice)?))
+        if slice.get_next_bit()? {
+            Ok(Self(X::construct_from_reference(slice)?, true))
+        } else {
+            Ok(Self(X::construct_from(slice)?, false))
+        }
     }
 }
 
-impl<X: Serializable> Serializable for Arc<X> {
+impl<X: Default + Deserializable + Serializable> Serializable for EitherInlineOrRef<X> {
     fn write_to(&self, cell: &mut BuilderData) -> Result<()> {
-        self.deref().write_to(cell)
+        if self.1 {
+            cell.append_bit_one()?;
+            cell.checked_append_reference(self.0.serialize()?)?;
+        } else {
+            cell.append_bit_zero()?;
+            self.0.write_to(cell)?;
+        }
+        Ok(())
     }
 }
 
-#[macro_export]
-macro_rules! define_HashmapE {
+/// Success value or error payload: tag bit 0 and T, or tag bit 1 and E
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Outcome<T, E>(pub std::result::Result<T, E>);
+
+impl<T: Default, E> Default for Outcome<T, E> {
+    fn default() -> Self {
+        Self(Ok(T::default()))
+    }
+}
+
+impl<T: Default + Deserializable, E: Default + Deserializable> Deserializable for Outcome<T, E> {
+    fn construct_from(slice: &mut SliceData) -> Result<Self> {
+        if slice.get_next_bit()? {
+            Ok(Self(Err(E::construct_from(slice)?)))
+        } else {
+            Ok(Self(Ok(T::construct_from(slice)?)))
+        }
+    }
+}
+
+impl<T: Serializable, E: Serializable> Serializable for Outcome<T, E> {
+    fn write_to(&self, cell: &mut BuilderData) -> Result<()> {
+        match &self.0 {
+            Ok(value) => {
+                cell.append_bit_zero()?;
+                value.write_to(cell)
+            }
+            Err(error) => {
+                cell.append_bit_one()?;
+                error.write_to(cell)
+            }
+        }
+    }
+}
+
+/// Referenced value like InRefValue but deserialized only on demand
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LazyRef<X: Default + Deserializable + Serializable> {
+    cell: Cell,
+    phantom: PhantomData<X>
+}
+
+impl<X: Default + Deserializable + Serializable> LazyRef<X> {
+    pub fn with_cell(cell: Cell) -> Self {
+        Self { cell, phantom: PhantomData }
+    }
+    pub fn with_value(value: &X) -> Result<Self> {
+        Ok(Self::with_cell(value.serialize()?))
+    }
+    pub fn resolve(&self) -> Result<X> {
+        if self.cell.cell_type() == CellType::PrunedBranch {
+            fail!(
+                BlockError::PrunedCellAccess(std::any::type_name::<X>().into())
+            )
+        }
+        X::construct_from_cell(self.cell.clone())
+    }
+    pub fn cell(&self) -> &Cell {
+        &self.cell
+    }
+    pub fn hash(&self) -> UInt256 {
+        self.cell.repr_hash()
+    }
+}
+
+impl<X: Default + Deserializable + Serializable> Deserializable for LazyRef<X> {
+    fn construct_from(slice: &mut SliceData) -> Result<Self> {
+        Ok(Self::with_cell(slice.checked_drain_reference()?))
+    }
+}
+
+impl<X: Default + Deserializable + Serializable> Serializable for LazyRef<X> {
+    fn write_to(&self, cell: &mut BuilderData) -> Result<()> {
+        cell.checked_append_reference(self.cell.clone())?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "deser-cache")]
+impl<X: Default + Deserializable + Serializable + Clone> InRefValue<X> {
+    pub fn construct_from_cached(slice: &mut SliceData, cache: &mut DeserCache<X>) -> Result<Self> {
+        Ok(Self(cache.get_or_construct(&slice.checked_drain_reference()?)?))
+    }
+}
+
+/// Bounded cache of deserialized values keyed by cell representation hash,
+/// cache is cleared when capacity is reached
+#[cfg(feature = "deser-cache")]
+#[derive(Debug)]
+pub struct DeserCache<T: Clone + Deserializable> {
+    capacity: usize,
+    items: std::collections::HashMap<UInt256, T>,
+    hits: usize,
+    misses: usize,
+}
+
+#[cfg(feature = "deser-cache")]
+impl<T: Clone + Deserializable> DeserCache<T> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity,
+            items: std::collections::HashMap::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+    pub fn get_or_construct(&mut self, cell: &Cell) -> Result<T> {
+        let hash = cell.repr_hash();
+        if let Some(value) = self.items.get(&hash) {
+            self.hits += 1;
+            return Ok(value.clone())
+        }
+        self.misses += 1;
+        let value = T::construct_from_cell(cell.clone())?;
+        if self.items.len() >= self.capacity {
+            self.items.clear();
+        }
+        if self.capacity != 0 {
+            self.items.insert(hash, value.clone());
+        }
+        Ok(value)
+    }
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+}
+
+impl<X: Default + Deserializable> Deserializable for Arc<X> {
+    fn construct_from(slice: &mut SliceData) -> Result<Self> {
+        Ok(Arc::new(X::construct_from(slice)?))
+    }
+}
+
+impl<X: Serializable> Serializable for Arc<X> {
+    fn write_to(&self, cell: &mut BuilderData) -> Result<()> {
+        self.deref().write_to(cell)
+    }
+}
+
+/// Item of merge join over two maps: key only in left, only in right or in both maps
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MergeItem<K, V> {
+    Left(K, V),
+    Right(K, V),
+    Both(K, V, V),
+}
+
+#[macro_export]
+macro_rules! define_HashmapE {
     ( $varname:ident, $bit_len:expr, $x_type:ty ) => {
         #[derive(PartialEq, Clone, Debug, Eq)]
         pub struct $varname(HashmapE);
@@ -762,6 +1548,14 @@ macro_rules! define_HashmapE {
             pub fn inner(self) -> HashmapE {
                 self.0
             }
+            /// key length in bits
+            pub fn bit_len(&self) -> usize {
+                $bit_len
+            }
+            /// seals map for cheap read-only sharing across threads
+            pub fn into_shared(self) -> std::sync::Arc<Self> {
+                std::sync::Arc::new(self)
+            }
             /// Used for not empty Hashmaps
             pub fn read_hashmap_root(&mut self, slice: &mut SliceData) -> Result<()> {
                 self.0.read_hashmap_root(slice)
@@ -770,6 +1564,22 @@ macro_rules! define_HashmapE {
             pub fn write_hashmap_root(&self, cell: &mut BuilderData) -> Result<()> {
                 self.0.write_hashmap_root(cell)
             }
+            /// Like read_hashmap_root, but leaves map empty if slice holds no data,
+            /// root is expected to occupy the rest of slice
+            pub fn read_hashmap_root_opt(&mut self, slice: &mut SliceData) -> Result<()> {
+                if slice.remaining_bits() == 0 && slice.remaining_references() == 0 {
+                    *self.0.data_mut() = None;
+                    return Ok(())
+                }
+                self.0.read_hashmap_root(slice)
+            }
+            /// Like write_hashmap_root, but writes nothing for empty map
+            pub fn write_hashmap_root_opt(&self, cell: &mut BuilderData) -> Result<()> {
+                if self.0.is_empty() {
+                    return Ok(())
+                }
+                self.0.write_hashmap_root(cell)
+            }
             /// Return true if no items
             pub fn is_empty(&self) -> bool {
                 self.0.is_empty()
@@ -789,11 +1599,100 @@ macro_rules! define_HashmapE {
             where F: FnMut($x_type) -> Result<bool> {
                 self.0.iterate_slices(|_, ref mut slice| p(<$x_type>::construct_from(slice)?))
             }
+            /// iterates items calling progress with running count after every `every` items
+            pub fn iterate_with_progress<F, P>(&self, p: &mut F, every: usize, mut progress: P) -> Result<bool>
+            where F: FnMut($x_type) -> Result<bool>, P: FnMut(usize) {
+                if every == 0 {
+                    ton_types::fail!(
+                        $crate::BlockError::InvalidArg("progress interval must not be zero".to_string())
+                    )
+                }
+                let mut count = 0;
+                self.iterate(|value| {
+                    let result = p(value)?;
+                    count += 1;
+                    if count % every == 0 {
+                        progress(count);
+                    }
+                    Ok(result)
+                })
+            }
+            /// counts items satisfying predicate
+            pub fn count_if<F>(&self, mut f: F) -> Result<usize>
+            where F: FnMut(&$x_type) -> Result<bool> {
+                let mut count = 0;
+                self.iterate(|value| {
+                    if f(&value)? {
+                        count += 1;
+                    }
+                    Ok(true)
+                })?;
+                Ok(count)
+            }
+            /// counts items as raw slices satisfying predicate
+            pub fn count_if_slice<F>(&self, mut f: F) -> Result<usize>
+            where F: FnMut(&SliceData) -> Result<bool> {
+                let mut count = 0;
+                self.iterate_slices(|slice| {
+                    if f(&slice)? {
+                        count += 1;
+                    }
+                    Ok(true)
+                })?;
+                Ok(count)
+            }
+            /// tallies items by bucket indices given by closure
+            pub fn histogram<F>(&self, mut bucket_of: F, num_buckets: usize) -> Result<Vec<usize>>
+            where F: FnMut(&$x_type) -> Result<usize> {
+                let mut buckets = vec![0; num_buckets];
+                self.iterate(|value| {
+                    let index = bucket_of(&value)?;
+                    match buckets.get_mut(index) {
+                        Some(count) => *count += 1,
+                        None => ton_types::fail!(
+                            $crate::BlockError::InvalidIndex(index)
+                        )
+                    }
+                    Ok(true)
+                })?;
+                Ok(buckets)
+            }
             /// iterates items as raw slices
             pub fn iterate_slices<F>(&self, mut p: F) -> Result<bool>
             where F: FnMut(SliceData) -> Result<bool> {
                 self.0.iterate_slices(|_, slice| p(slice))
             }
+            /// iterates items as raw slices with their bit lengths
+            pub fn iterate_by_value_size<F>(&self, p: &mut F) -> Result<bool>
+            where F: FnMut(SliceData, usize) -> Result<bool> {
+                self.0.iterate_slices(|_, slice| {
+                    let bits = slice.remaining_bits();
+                    p(slice, bits)
+                })
+            }
+            /// returns min, max and average bit length of items
+            pub fn value_size_stats(&self) -> Result<(usize, usize, f64)> {
+                let (mut min, mut max, mut total, mut count) = (usize::MAX, 0, 0, 0);
+                self.iterate_by_value_size(&mut |_, bits| {
+                    min = std::cmp::min(min, bits);
+                    max = std::cmp::max(max, bits);
+                    total += bits;
+                    count += 1;
+                    Ok(true)
+                })?;
+                if count == 0 {
+                    return Ok((0, 0, 0.0))
+                }
+                Ok((min, max, total as f64 / count as f64))
+            }
+            /// iterates items as slices with keys starting with given bit prefix
+            pub fn iterate_prefix<F>(&self, prefix: &SliceData, p: &mut F) -> Result<bool>
+            where F: FnMut(SliceData, SliceData) -> Result<bool> {
+                use ton_types::HashmapSubtree;
+                let mut subtree = self.0.clone();
+                subtree.into_subtree_with_prefix(prefix, &mut 0)?;
+                subtree.iterate_slices(|key, slice| p(key, slice))
+            }
             /// iterates keys
             pub fn iterate_keys<K, F>(&self, mut p: F) -> Result<bool>
             where K: Default + Deserializable, F: FnMut(K) -> Result<bool> {
@@ -814,12 +1713,31 @@ macro_rules! define_HashmapE {
             where F: FnMut(SliceData, SliceData) -> Result<bool> {
                 self.0.iterate_slices(|key, slice| p(key, slice))
             }
+            /// returns iterator of items with keys over snapshot of raw slices
+            pub fn pairs<K: Default + Deserializable>(&self) -> impl Iterator<Item = Result<(K, $x_type)>> {
+                let mut items = Vec::new();
+                let error = self.0.iterate_slices(|key, slice| {
+                    items.push(Ok((key, slice)));
+                    Ok(true)
+                }).err();
+                items.extend(error.map(Err));
+                items.into_iter().map(|item: Result<(SliceData, SliceData)>| {
+                    let (mut key, mut slice) = item?;
+                    Ok((K::construct_from(&mut key)?, <$x_type>::construct_from(&mut slice)?))
+                })
+            }
             pub fn set<K: Serializable>(&mut self, key: &K, value: &$x_type) -> Result<()> {
                 let key = key.serialize()?.into();
                 let value = value.write_to_new_cell()?;
                 self.0.set_builder(key, &value)?;
                 Ok(())
             }
+            /// sets pre-serialized value as is
+            pub fn set_slice<K: Serializable>(&mut self, key: &K, value: SliceData) -> Result<()> {
+                let key = key.serialize()?.into();
+                self.0.set(key, &value)?;
+                Ok(())
+            }
             pub fn setref<K: Serializable>(&mut self, key: &K, value: &Cell) -> Result<()> {
                 let key = key.serialize()?.into();
                 self.0.setref(key, value)?;
@@ -835,10 +1753,62 @@ macro_rules! define_HashmapE {
                 self.get_as_slice(key)?
                     .map(|ref mut slice| <$x_type>::construct_from(slice)).transpose()
             }
+            /// sets new value only if current one equals expected, None expects absent key,
+            /// values are compared by serialized representation, returns if value is written
+            pub fn compare_and_set<K: Serializable>(
+                &mut self,
+                key: &K,
+                expected: Option<&$x_type>,
+                new: &$x_type
+            ) -> Result<bool> {
+                let current = self.get_as_slice(key)?
+                    .map(|slice| slice.into_cell().repr_hash());
+                let expected = expected
+                    .map(|value| value.serialize().map(|cell| cell.repr_hash()))
+                    .transpose()?;
+                if current != expected {
+                    return Ok(false)
+                }
+                self.set(key, new)?;
+                Ok(true)
+            }
+            /// gets items for several keys in the same order, queries are sorted by key
+            pub fn get_many<K: Serializable>(&self, keys: &[K]) -> Result<Vec<Option<$x_type>>> {
+                let mut queries = Vec::with_capacity(keys.len());
+                for (index, key) in keys.iter().enumerate() {
+                    queries.push((key.serialize()?, index));
+                }
+                queries.sort_by(|(a, _), (b, _)| a.data().cmp(b.data()));
+                let mut result = Vec::new();
+                result.resize_with(keys.len(), || None);
+                for (key, index) in queries {
+                    result[index] = self.get_raw(key.into())?
+                        .map(|ref mut slice| <$x_type>::construct_from(slice)).transpose()?;
+                }
+                Ok(result)
+            }
             pub fn get_as_slice<K: Serializable>(&self, key: &K) -> Result<Option<SliceData>> {
                 let key = key.serialize()?.into();
                 self.get_raw(key)
             }
+            /// gets bit range of item as integer without deserialization
+            pub fn get_value_bits<K: Serializable>(&self, key: &K, bit_offset: usize, bit_len: usize) -> Result<Option<u64>> {
+                match self.get_as_slice(key)? {
+                    Some(mut slice) => {
+                        if bit_len > 64 || bit_offset + bit_len > slice.remaining_bits() {
+                            ton_types::fail!(
+                                $crate::BlockError::InvalidArg(
+                                    format!("bits {}..{} are out of value with {} bits",
+                                        bit_offset, bit_offset + bit_len, slice.remaining_bits())
+                                )
+                            )
+                        }
+                        slice.shrink_data(bit_offset..);
+                        Ok(Some(slice.get_next_int(bit_len)?))
+                    }
+                    None => Ok(None)
+                }
+            }
             pub fn get_raw(&self, key: SliceData) -> Result<Option<SliceData>> {
                 self.0.get(key)
             }
@@ -847,6 +1817,113 @@ macro_rules! define_HashmapE {
                 let leaf = self.0.remove(key)?;
                 Ok(leaf.is_some())
             }
+            // items as key bits, key and value slices in dictionary order
+            fn sorted_items(&self) -> Result<Vec<(Vec<u8>, SliceData, SliceData)>> {
+                let bit_len = self.0.bit_len();
+                let mut items = Vec::new();
+                self.0.iterate_slices(|key, value| {
+                    items.push((key.clone().get_next_bits(bit_len)?, key, value));
+                    Ok(true)
+                })?;
+                Ok(items)
+            }
+            fn merge_keys<K>(&self, other: &Self, left: bool, both: bool, right: bool) -> Result<Vec<K>>
+            where K: Default + Serializable + Deserializable {
+                let (a, b) = (self.sorted_items()?, other.sorted_items()?);
+                let (mut i, mut j) = (0, 0);
+                let mut result = Vec::new();
+                while i < a.len() || j < b.len() {
+                    let order = match (a.get(i), b.get(j)) {
+                        (Some(x), Some(y)) => x.0.cmp(&y.0),
+                        (Some(_), None) => std::cmp::Ordering::Less,
+                        _ => std::cmp::Ordering::Greater
+                    };
+                    let (take, key) = match order {
+                        std::cmp::Ordering::Less => { i += 1; (left, &a[i - 1].1) }
+                        std::cmp::Ordering::Greater => { j += 1; (right, &b[j - 1].1) }
+                        std::cmp::Ordering::Equal => { i += 1; j += 1; (both, &a[i - 1].1) }
+                    };
+                    if take {
+                        result.push(K::construct_from(&mut key.clone())?);
+                    }
+                }
+                Ok(result)
+            }
+            /// walks both maps in dictionary order of key bits passing matched and unmatched items,
+            /// for signed keys it differs from ascending order of key values
+            pub fn merge_join<K, F>(&self, other: &Self, mut f: F) -> Result<()>
+            where
+                K: Default + Serializable + Deserializable,
+                F: FnMut($crate::MergeItem<K, $x_type>) -> Result<()>
+            {
+                let (a, b) = (self.sorted_items()?, other.sorted_items()?);
+                let (mut a, mut b) = (a.into_iter().peekable(), b.into_iter().peekable());
+                loop {
+                    let order = match (a.peek(), b.peek()) {
+                        (Some(x), Some(y)) => x.0.cmp(&y.0),
+                        (Some(_), None) => std::cmp::Ordering::Less,
+                        (None, Some(_)) => std::cmp::Ordering::Greater,
+                        (None, None) => return Ok(())
+                    };
+                    let item = match order {
+                        std::cmp::Ordering::Less => {
+                            let (_, mut key, mut value) = a.next().unwrap();
+                            $crate::MergeItem::Left(K::construct_from(&mut key)?, <$x_type>::construct_from(&mut value)?)
+                        }
+                        std::cmp::Ordering::Greater => {
+                            let (_, mut key, mut value) = b.next().unwrap();
+                            $crate::MergeItem::Right(K::construct_from(&mut key)?, <$x_type>::construct_from(&mut value)?)
+                        }
+                        std::cmp::Ordering::Equal => {
+                            let (_, mut key, mut left) = a.next().unwrap();
+                            let (_, _, mut right) = b.next().unwrap();
+                            $crate::MergeItem::Both(
+                                K::construct_from(&mut key)?,
+                                <$x_type>::construct_from(&mut left)?,
+                                <$x_type>::construct_from(&mut right)?
+                            )
+                        }
+                    };
+                    f(item)?;
+                }
+            }
+            /// returns keys present in both maps in dictionary order
+            pub fn intersect_keys<K>(&self, other: &Self) -> Result<Vec<K>>
+            where K: Default + Serializable + Deserializable {
+                self.merge_keys(other, false, true, false)
+            }
+            /// returns keys present in any of maps in dictionary order
+            pub fn union_keys<K>(&self, other: &Self) -> Result<Vec<K>>
+            where K: Default + Serializable + Deserializable {
+                self.merge_keys(other, true, true, true)
+            }
+            /// returns keys present in this map but not in other in dictionary order
+            pub fn difference_keys<K>(&self, other: &Self) -> Result<Vec<K>>
+            where K: Default + Serializable + Deserializable {
+                self.merge_keys(other, true, false, false)
+            }
+            /// removes items with keys in [start, end) in dictionary order, returns count of removed items
+            pub fn remove_range<K>(&mut self, start: &K, end: &K) -> Result<usize>
+            where K: Default + Serializable + Deserializable {
+                let bit_len = self.0.bit_len();
+                let start = SliceData::from(start.serialize()?).get_next_bits(bit_len)?;
+                let end = SliceData::from(end.serialize()?).get_next_bits(bit_len)?;
+                let mut keys = Vec::new();
+                self.0.iterate_slices(|key, _| {
+                    let bits = key.clone().get_next_bits(bit_len)?;
+                    if bits >= end {
+                        return Ok(false)
+                    }
+                    if bits >= start {
+                        keys.push(key);
+                    }
+                    Ok(true)
+                })?;
+                for key in &keys {
+                    self.0.remove(key.clone())?;
+                }
+                Ok(keys.len())
+            }
             pub fn check_key<K: Serializable>(&self, key: &K) -> Result<bool> {
                 let key = key.serialize()?.into();
                 self.0.get(key).map(|value| value.is_some())
@@ -891,6 +1968,145 @@ macro_rules! define_HashmapE {
                 Ok(())
             }
 
+            /// returns first item in key order satisfying predicate
+            pub fn find<K, F>(&self, mut f: F) -> Result<Option<(K, $x_type)>>
+            where K: Default + Deserializable, F: FnMut(&K, &$x_type) -> Result<bool> {
+                let mut found = None;
+                self.iterate_with_keys(|key: K, value| {
+                    if f(&key, &value)? {
+                        found = Some((key, value));
+                        return Ok(false)
+                    }
+                    Ok(true)
+                })?;
+                Ok(found)
+            }
+
+            /// compares items with given pairs, stops on first mismatch
+            pub fn eq_pairs<K, I>(&self, pairs: I) -> Result<bool>
+            where
+                K: Default + Serializable + Deserializable + Ord,
+                I: IntoIterator<Item = (K, $x_type)>
+            {
+                let mut expected = std::collections::BTreeMap::new();
+                for (key, value) in pairs {
+                    if expected.insert(key, value.serialize()?).is_some() {
+                        return Ok(false) // duplicate keys can not match map
+                    }
+                }
+                let mut count = 0;
+                let equal = self.0.iterate_slices(|mut key, slice| {
+                    count += 1;
+                    let key = K::construct_from(&mut key)?;
+                    Ok(expected.get(&key) == Some(&slice.into_cell()))
+                })?;
+                Ok(equal && count == expected.len())
+            }
+
+            /// builds new map with transformed keys, fails if two keys collide
+            pub fn map_keys<K, F>(&self, mut f: F) -> Result<Self>
+            where K: Default + Serializable + Deserializable, F: FnMut(K) -> Result<K> {
+                let mut map = HashmapE::with_bit_len($bit_len);
+                self.0.iterate_slices(|mut key, slice| {
+                    let key = f(K::construct_from(&mut key)?)?.serialize()?;
+                    if map.set(key.clone().into(), &slice)?.is_some() {
+                        ton_types::fail!(
+                            $crate::BlockError::InvalidArg(
+                                format!("key collision on {}", SliceData::from(key).to_hex_string())
+                            )
+                        )
+                    }
+                    Ok(true)
+                })?;
+                Ok(Self(map))
+            }
+
+            /// collapses runs of consecutive keys with equal values into (start, end, value) ranges,
+            /// values are compared by serialized representation
+            pub fn to_ranges<K>(&self) -> Result<Vec<(K, K, $x_type)>>
+            where K: Default + Deserializable + Ord {
+                let bit_len = self.0.bit_len();
+                let successor = |bits: &[u8]| -> Option<Vec<u8>> {
+                    let mut bits = bits.to_vec();
+                    for i in (0..bit_len).rev() {
+                        let mask = 0x80 >> (i % 8);
+                        if bits[i / 8] & mask == 0 {
+                            bits[i / 8] |= mask;
+                            return Some(bits)
+                        }
+                        bits[i / 8] &= !mask;
+                    }
+                    None
+                };
+                let mut ranges = Vec::new();
+                // key bits and value hash of the last item in current range
+                let mut last: Option<(Vec<u8>, ton_types::UInt256)> = None;
+                self.0.iterate_slices(|key, mut slice| {
+                    let bits = key.clone().get_next_bits(bit_len)?;
+                    let hash = slice.clone().into_cell().repr_hash();
+                    let extends = match &last {
+                        Some((last_bits, last_hash)) => {
+                            last_hash == &hash && successor(last_bits).as_ref() == Some(&bits)
+                        }
+                        None => false
+                    };
+                    if extends {
+                        if let Some(range) = ranges.last_mut() {
+                            let range: &mut (K, K, $x_type) = range;
+                            range.1 = K::construct_from(&mut key.clone())?;
+                        }
+                    } else {
+                        let start = K::construct_from(&mut key.clone())?;
+                        let end = K::construct_from(&mut key.clone())?;
+                        ranges.push((start, end, <$x_type>::construct_from(&mut slice)?));
+                    }
+                    last = Some((bits, hash));
+                    Ok(true)
+                })?;
+                Ok(ranges)
+            }
+
+            /// rebuilds map as plain HashmapE with keys zero-extended to new_bit_len on high or low side
+            pub fn rebit(&self, new_bit_len: usize, extend_high: bool) -> Result<HashmapE> {
+                let bit_len = self.0.bit_len();
+                if new_bit_len < bit_len {
+                    ton_types::fail!(
+                        $crate::BlockError::InvalidArg(
+                            format!("new key length {} is less than {}", new_bit_len, bit_len)
+                        )
+                    )
+                }
+                let pad = new_bit_len - bit_len;
+                let zeros = vec![0; (pad + 7) / 8];
+                let mut map = HashmapE::with_bit_len(new_bit_len);
+                self.0.iterate_slices(|mut key, value| {
+                    let bits = key.get_next_bits(bit_len)?;
+                    let mut new_key = BuilderData::new();
+                    if extend_high {
+                        new_key.append_raw(&zeros, pad)?;
+                        new_key.append_raw(&bits, bit_len)?;
+                    } else {
+                        new_key.append_raw(&bits, bit_len)?;
+                        new_key.append_raw(&zeros, pad)?;
+                    }
+                    map.set(new_key.into_cell()?.into(), &value)?;
+                    Ok(true)
+                })?;
+                Ok(map)
+            }
+
+            /// builds new map with the same keys and transformed values
+            pub fn map_values<F>(&self, mut f: F) -> Result<Self>
+            where F: FnMut($x_type) -> Result<$x_type> {
+                let mut map = HashmapE::with_bit_len($bit_len);
+                self.0.iterate_slices(|key, ref mut slice| {
+                    let value = f(<$x_type>::construct_from(slice)?)?;
+                    map.set_builder(key, &value.write_to_new_cell()?)?;
+                    Ok(true)
+                })?;
+                Ok(Self(map))
+            }
+
             pub fn export_keys<K: Deserializable>(&self) -> Result<Vec<K>> {
                 let mut keys = Vec::new();
                 self.iterate_keys(|key: K| {
@@ -917,8 +2133,144 @@ macro_rules! define_HashmapE {
                     Ok(None)
                 }
             }
+
+            /// Exports bitmap of present keys, bit index is key value (for keys up to 16 bits),
+            /// key_bits must be equal to the map's key length
+            pub fn presence_bitmap(&self, key_bits: usize) -> Result<Vec<u8>> {
+                let bit_len = self.0.bit_len();
+                if key_bits != bit_len {
+                    ton_types::fail!(
+                        $crate::BlockError::InvalidArg(
+                            format!("key length {} does not match map key length {}", key_bits, bit_len)
+                        )
+                    )
+                }
+                if bit_len > 16 {
+                    ton_types::fail!(
+                        $crate::BlockError::InvalidOperation(
+                            format!("key space of {} bits is too large for bitmap", bit_len)
+                        )
+                    )
+                }
+                let mut bitmap = vec![0u8; ((1usize << bit_len) + 7) / 8];
+                self.0.iterate_slices(|mut key, _| {
+                    let index = key.get_next_int(bit_len)? as usize;
+                    bitmap[index / 8] |= 0x80 >> (index % 8);
+                    Ok(true)
+                })?;
+                Ok(bitmap)
+            }
+
+            /// Builds one merkle proof covering paths to all given keys
+            pub fn prove_many<K: Serializable>(&self, keys: &[K]) -> Result<Cell> {
+                let root = match self.0.data() {
+                    Some(root) => root,
+                    None => ton_types::fail!(
+                        $crate::BlockError::InvalidOperation("cannot prove keys for empty map".to_string())
+                    )
+                };
+                let usage_tree = ton_types::UsageTree::with_root(root.clone());
+                let map = HashmapE::with_hashmap(self.0.bit_len(), Some(usage_tree.root_cell()));
+                for key in keys {
+                    map.get(key.serialize()?.into())?;
+                }
+                $crate::MerkleProof::create_by_usage_tree(root, usage_tree)?.serialize()
+            }
+
+            /// Rebuilds map inserting items in ascending key order and returns its root,
+            /// so equal sets of items always give equal root hashes
+            pub fn canonical_cell(&self) -> Result<Option<Cell>> {
+                let mut map = HashmapE::with_bit_len(self.0.bit_len());
+                self.0.iterate_slices(|key, slice| {
+                    map.set(key, &slice)?;
+                    Ok(true)
+                })?;
+                Ok(map.data().cloned())
+            }
+
+            /// Returns root hash, or all-zeros hash for empty map so all empty maps give the same value
+            pub fn root_hash_or_empty(&self) -> ton_types::UInt256 {
+                match self.0.data() {
+                    Some(root) => root.repr_hash(),
+                    None => ton_types::UInt256::default()
+                }
+            }
+
+            /// Calculates sha256 of root hash (zero hash for empty map) followed by salt
+            pub fn salted_hash(&self, salt: &[u8]) -> Result<ton_types::UInt256> {
+                Ok($crate::salted_root_hash(&self.root_hash_or_empty(), salt))
+            }
+
+            /// Dumps internal tree structure with key prefixes, branch bits and leaf value hashes
+            pub fn dump_tree(&self) -> Result<String> {
+                let mut out = String::new();
+                match self.0.data() {
+                    Some(root) => Self::dump_node(&mut out, root, self.0.bit_len(), String::new(), 0)?,
+                    None => out.push_str("empty\n")
+                }
+                Ok(out)
+            }
+
+            fn dump_node(out: &mut String, cell: &Cell, bit_len: usize, mut prefix: String, indent: usize) -> Result<()> {
+                let mut slice = SliceData::from(cell);
+                let mut label = slice.get_label(bit_len)?;
+                let label_len = label.remaining_bits();
+                let mut label_bits = String::new();
+                while label.remaining_bits() != 0 {
+                    label_bits.push(if label.get_next_bit()? { '1' } else { '0' });
+                }
+                prefix.push_str(&label_bits);
+                let pad = "  ".repeat(indent);
+                if label_len == bit_len {
+                    out.push_str(&format!("{}leaf key: {} value: {:x}\n", pad, prefix, slice.into_cell().repr_hash()));
+                } else if label_len < bit_len {
+                    out.push_str(&format!("{}fork prefix: {} label: {}\n", pad, prefix, label_bits));
+                    for bit in 0..2 {
+                        let child = slice.reference(bit)?;
+                        let prefix = format!("{}{}", prefix, bit);
+                        Self::dump_node(out, &child, bit_len - label_len - 1, prefix, indent + 1)?;
+                    }
+                } else {
+                    ton_types::fail!(
+                        $crate::BlockError::InvalidData("label_length > bit_len".to_string())
+                    )
+                }
+                Ok(())
+            }
+
+            /// returns cells of this map tree absent by hash in previous version,
+            /// which is enough to reconstruct this map for holder of previous one
+            pub fn changed_cells(&self, previous: &Self) -> Result<Vec<Cell>> {
+                let mut known = std::collections::HashSet::new();
+                let mut stack: Vec<Cell> = previous.0.data().cloned().into_iter().collect();
+                while let Some(cell) = stack.pop() {
+                    if known.insert(cell.repr_hash()) {
+                        for i in 0..cell.references_count() {
+                            stack.push(cell.reference(i)?);
+                        }
+                    }
+                }
+                let mut changed = Vec::new();
+                let mut stack: Vec<Cell> = self.0.data().cloned().into_iter().collect();
+                while let Some(cell) = stack.pop() {
+                    // known subtrees are shared entirely, so they are skipped
+                    if known.insert(cell.repr_hash()) {
+                        for i in 0..cell.references_count() {
+                            stack.push(cell.reference(i)?);
+                        }
+                        changed.push(cell);
+                    }
+                }
+                Ok(changed)
+            }
         }
 
+        // maps are shared across threads, so check it at compile time
+        const _: fn() = || {
+            fn assert_send_sync<T: Send + Sync>() {}
+            assert_send_sync::<$varname>();
+        };
+
         impl Default for $varname {
             fn default() -> Self {
                 $varname(HashmapE::with_bit_len($bit_len))
@@ -939,6 +2291,102 @@ macro_rules! define_HashmapE {
     }
 }
 
+/// Generates dictionary with count random keys of bit_len bits reproducible by seed,
+/// values are produced by generator from the same random source. ChaCha8 output
+/// does not change across releases, so the same seed gives the same dictionary
+#[cfg(feature = "test-util")]
+pub fn generate_random_hashmap<V, F>(bit_len: usize, count: usize, seed: u64, mut value: F) -> Result<HashmapE>
+where
+    V: Serializable,
+    F: FnMut(&mut rand_chacha::ChaCha8Rng) -> Result<V>
+{
+    use rand_chacha::rand_core::{RngCore, SeedableRng};
+    if 1u64.checked_shl(bit_len as u32).map_or(false, |max| count as u64 > max) {
+        fail!(BlockError::InvalidArg(format!("{} keys do not fit into {} bits", count, bit_len)))
+    }
+    let mut rng_seed = [0; 32];
+    rng_seed[..8].copy_from_slice(&seed.to_le_bytes());
+    let mut rng = rand_chacha::ChaCha8Rng::from_seed(rng_seed);
+    let mut map = HashmapE::with_bit_len(bit_len);
+    let mut len = 0;
+    while len < count {
+        let mut key = vec![0; (bit_len + 7) / 8];
+        rng.fill_bytes(&mut key);
+        let value = value(&mut rng)?.write_to_new_cell()?;
+        if map.set_builder(SliceData::from_raw(key, bit_len), &value)?.is_none() {
+            len += 1;
+        }
+    }
+    Ok(map)
+}
+
+/// Calculates sha256 of root hash followed by salt
+pub fn salted_root_hash(root_hash: &UInt256, salt: &[u8]) -> UInt256 {
+    use sha2::Digest;
+    let mut hasher = sha2::Sha256::new();
+    hasher.input(root_hash.as_slice());
+    hasher.input(salt);
+    let hash: [u8; 32] = hasher.result().into();
+    hash.into()
+}
+
+/// Compares hashes without early exit on first differing byte, use it for security-sensitive values.
+/// Accumulator is passed through black_box on each step so optimizer can not turn loop
+/// into early-exit comparison, it is best effort and not a hard constant-time guarantee
+pub fn ct_eq(a: &UInt256, b: &UInt256) -> bool {
+    let diff = a.as_slice().iter()
+        .zip(b.as_slice().iter())
+        .fold(0u8, |acc, (x, y)| std::hint::black_box(acc | (x ^ y)));
+    std::hint::black_box(diff) == 0
+}
+
+/// Debugging aid for encoding mismatches: walks both trees in depth-first order
+/// and returns offset of the first differing bit and index of the cell containing it
+/// in walk order (root is 0). Differing references count is reported at offset
+/// equal to bit length of the cell. Returns None if trees are identical
+pub fn cell_bit_diff(a: &Cell, b: &Cell) -> Option<(usize, usize)> {
+    let mut index = 0;
+    cell_bit_diff_from(a, b, &mut index)
+}
+
+fn cell_bit_diff_from(a: &Cell, b: &Cell, index: &mut usize) -> Option<(usize, usize)> {
+    if a.repr_hash() == b.repr_hash() {
+        *index += count_cells(a);
+        return None
+    }
+    let (data_a, data_b) = (a.data(), b.data());
+    let common = std::cmp::min(a.bit_length(), b.bit_length());
+    for bit in 0..common {
+        let mask = 0x80 >> (bit % 8);
+        if data_a[bit / 8] & mask != data_b[bit / 8] & mask {
+            return Some((bit, *index))
+        }
+    }
+    if a.bit_length() != b.bit_length() {
+        return Some((common, *index))
+    }
+    if a.references_count() != b.references_count() {
+        return Some((a.bit_length(), *index))
+    }
+    let current = *index;
+    *index += 1;
+    for i in 0..a.references_count() {
+        if let (Ok(a), Ok(b)) = (a.reference(i), b.reference(i)) {
+            if let Some(diff) = cell_bit_diff_from(&a, &b, index) {
+                return Some(diff)
+            }
+        }
+    }
+    // trees differ only in cell types or levels
+    Some((a.bit_length(), current))
+}
+
+fn count_cells(cell: &Cell) -> usize {
+    (0..cell.references_count())
+        .filter_map(|i| cell.reference(i).ok())
+        .fold(1, |count, child| count + count_cells(&child))
+}
+
 #[derive(PartialEq, Copy, Clone, Debug, Eq, Default, Hash)]
 pub struct UnixTime32(pub u32);
 
@@ -976,6 +2424,37 @@ impl Display for UnixTime32 {
 	}
 }
 
+#[cfg(feature = "serde")]
+impl serde::Serialize for UnixTime32 {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for UnixTime32 {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Ok(Self(<u32 as serde::Deserialize>::deserialize(deserializer)?))
+    }
+}
+
+/// Duration is stored as whole seconds in uint32, sub-second precision is dropped
+impl Serializable for Duration {
+    fn write_to(&self, cell: &mut BuilderData) -> Result<()> {
+        let secs = self.as_secs();
+        if secs > u32::MAX as u64 {
+            fail!(BlockError::InvalidArg(format!("duration {} seconds does not fit into u32", secs)))
+        }
+        (secs as u32).write_to(cell)
+    }
+}
+
+impl Deserializable for Duration {
+    fn construct_from(slice: &mut SliceData) -> Result<Self> {
+        Ok(Duration::from_secs(slice.get_next_u32()? as u64))
+    }
+}
+
 #[derive(Debug, Default, Clone, Eq)]
 pub struct ChildCell<T: Default + Serializable + Deserializable> {
     cell: Option<Cell>,
@@ -1024,6 +2503,21 @@ impl<T: Default + Serializable + Deserializable + Clone> ChildCell<T> {
         }
     }
 
+    #[cfg(feature = "deser-cache")]
+    pub fn read_struct_cached(&self, cache: &mut DeserCache<T>) -> Result<T> {
+        match self.cell.as_ref() {
+            Some(cell) => {
+                if cell.cell_type() == CellType::PrunedBranch {
+                    fail!(
+                        BlockError::PrunedCellAccess(std::any::type_name::<T>().into())
+                    )
+                }
+                cache.get_or_construct(cell)
+            }
+            None => Ok(T::default())
+        }
+    }
+
     pub fn read_from_reference(&mut self, slice: &mut SliceData) -> Result<()> {
         self.cell = Some(slice.checked_drain_reference()?);
         Ok(())
@@ -1034,6 +2528,20 @@ impl<T: Default + Serializable + Deserializable + Clone> ChildCell<T> {
         Ok(Self::with_cell(cell))
     }
 
+    pub fn read_from_expecting(&mut self, slice: &mut SliceData, expected: CellType) -> Result<()> {
+        let cell = slice.checked_drain_reference()?;
+        if cell.cell_type() != expected {
+            fail!(
+                BlockError::InvalidData(
+                    format!("{} must be in {:?} cell, but it is {:?}",
+                        std::any::type_name::<T>(), expected, cell.cell_type())
+                )
+            )
+        }
+        self.cell = Some(cell);
+        Ok(())
+    }
+
     pub fn cell(&self)-> Cell {
         match self.cell.as_ref() {
             Some(cell) => cell.clone(),
@@ -1051,6 +2559,21 @@ impl<T: Default + Serializable + Deserializable + Clone> ChildCell<T> {
             None => T::default().serialize().unwrap().repr_hash()
         }
     }
+
+    pub fn reference_count(&self) -> usize {
+        self.cell().references_count()
+    }
+
+    pub fn bit_length(&self) -> usize {
+        self.cell().bit_length()
+    }
+
+    /// Replaces stored cell with pooled one of equal hash, so equal children share one cell
+    pub fn intern(&mut self, pool: &mut std::collections::HashMap<UInt256, Cell>) {
+        let cell = self.cell();
+        let cell = pool.entry(cell.repr_hash()).or_insert(cell).clone();
+        self.cell = Some(cell);
+    }
 }
 
 impl<T: Default + Serializable + Deserializable> PartialEq for ChildCell<T> {
@@ -1081,3 +2604,47 @@ impl<T: Default + Serializable + Deserializable> Serializable for ChildCell<T> {
         Ok(())
     }
 }
+
+/// Fixed number of child cells stored as references
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ChildCellArray<T: Default + Serializable + Deserializable, const N: usize>(pub [ChildCell<T>; N]);
+
+impl<T: Default + Serializable + Deserializable, const N: usize> Default for ChildCellArray<T, N> {
+    fn default() -> Self {
+        Self(std::array::from_fn(|_| <ChildCell<T> as Default>::default()))
+    }
+}
+
+impl<T: Default + Serializable + Deserializable + Clone, const N: usize> ChildCellArray<T, N> {
+    fn check_len() -> Result<()> {
+        if N > 4 {
+            fail!(
+                BlockError::InvalidArg(format!("cell can't hold {} references", N))
+            )
+        }
+        Ok(())
+    }
+}
+
+impl<T: Default + Serializable + Deserializable + Clone, const N: usize> Serializable for ChildCellArray<T, N> {
+    fn write_to(&self, cell: &mut BuilderData) -> Result<()> {
+        Self::check_len()?;
+        for child in self.0.iter() {
+            cell.checked_append_reference(child.cell())?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Default + Serializable + Deserializable + Clone, const N: usize> Deserializable for ChildCellArray<T, N> {
+    fn construct_from(slice: &mut SliceData) -> Result<Self> {
+        Self::check_len()?;
+        let mut children = Vec::with_capacity(N);
+        for _ in 0..N {
+            children.push(ChildCell::construct_from_reference(slice)?);
+        }
+        let children = children.try_into()
+            .map_err(|_| error!("cannot collect {} child cells", N))?;
+        Ok(Self(children))
+    }
+}